@@ -0,0 +1,25 @@
+use flappy_fellers::{
+    HEADLESS_HEIGHT, HEADLESS_WIDTH, parse_path_flag, parse_topology, parse_train_generations,
+    save_champion, train,
+};
+
+/// Headless trainer: runs the evolution loop with no rendering and no
+/// macroquad window, so it can run on a display-less machine (e.g. CI).
+/// Usage: `train --train <generations> [--topology 5,4,2] [--save champion.json]`
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    let Some(generations) = parse_train_generations(&args) else {
+        eprintln!("usage: train --train <generations> [--topology 5,4,2] [--save champion.json]");
+        std::process::exit(1);
+    };
+    let topology = parse_topology(&args);
+    let save_path = parse_path_flag(&args, "--save");
+
+    let champion = train(generations, HEADLESS_WIDTH, HEADLESS_HEIGHT, &topology);
+
+    match &save_path {
+        Some(path) => save_champion(path, &champion),
+        None => eprintln!("no --save path given; trained champion brain will not be persisted"),
+    }
+}
@@ -0,0 +1,663 @@
+//! Core simulation and evolution logic for Flappy Fellers, shared by the
+//! graphical binary (`src/main.rs`) and the headless trainer
+//! (`src/bin/train.rs`). Nothing in this crate depends on macroquad, so
+//! training can run without opening a window or touching a display/GPU.
+
+use neural_network_study::{ActivationFunction, NeuralNetwork};
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+/// Speed at which the pipes move in pixels per iteration
+const HSPEED: f32 = 0.8;
+/// Maximum vertical speed of a flappy feller
+const FELLER_MAX_SPEED: f32 = 2.0;
+/// Probability of spawning a pipe during an iteration
+const PIPE_PROBABILITY: f32 = 0.002;
+/// Pipe width
+pub const PIPE_WIDTH: f32 = 40.0;
+/// Minimum size of the pipe aperture (hole)
+const PIPE_MIN_APERTURE: f32 = 80.0;
+/// Maximum size of the pipe aperture
+const PIPE_MAX_APERTURE: f32 = 160.0;
+/// Minimum distance between two pipes
+const PIPE_MIN_DISTANCE: f32 = 160.0;
+/// Jumping force
+const LIFT: f32 = 2.0;
+/// x-coordinate of the fellers
+pub const FELLER_X: f32 = 40.0;
+/// body radius of the fellers
+const FELLER_R: f32 = 20.0;
+/// Number of fellers in each generation
+pub const POPULATION_SIZE: usize = 150;
+/// Probability of mutation of weights
+/// during cloning of neural network
+const MUTATION_RATE: f64 = 0.1;
+/// Logical screen width used for headless training, where there is
+/// no window to query `screen_width()` from
+pub const HEADLESS_WIDTH: f32 = 800.0;
+/// Logical screen height used for headless training, where there is
+/// no window to query `screen_height()` from
+pub const HEADLESS_HEIGHT: f32 = 600.0;
+/// Default network topology (5 inputs, one 4-node hidden layer, 2
+/// outputs), used unless overridden with `--topology`
+pub const DEFAULT_TOPOLOGY: &[usize] = &[5, 4, 2];
+/// Bonus added to a feller's score for every pipe it has flown through,
+/// on top of its raw survival time
+const PIPE_BONUS: f32 = 100.0;
+
+/// Run `generations` generations of the simulation with no rendering, using
+/// a fixed logical screen size instead of `screen_width()`/`screen_height()`.
+/// Prints the best score of each generation and returns the best feller
+/// found across all of them. Callers must pass `generations > 0`.
+pub fn train(generations: usize, width: f32, height: f32, topology: &[usize]) -> Feller {
+    let mut rng = StdRng::from_os_rng();
+    let mut population = Population::new(POPULATION_SIZE, height, topology);
+    let mut best: Option<Feller> = None;
+
+    for generation in 1..=generations {
+        let mut pipes: Vec<Pipe> = vec![];
+        let mut step = 0;
+        while population.is_alive() {
+            simulate_step(&mut pipes, population.fellers_mut(), &mut rng, step, width, height);
+            step += 1;
+        }
+
+        let champion = population.champion();
+        println!("generation {generation}: best score = {}", score(champion));
+        if best.as_ref().is_none_or(|b| score(champion) > score(b)) {
+            best = Some(champion.clone());
+        }
+
+        population.advance_generation(height);
+    }
+
+    best.expect("train should be called with at least one generation")
+}
+
+/// Parse the `--topology` flag into a layer-size vector, falling back
+/// to `DEFAULT_TOPOLOGY` if it's missing, contains an empty layer, or
+/// is incompatible with the fixed number of game inputs (5) and
+/// outputs (2)
+pub fn parse_topology(args: &[String]) -> Vec<usize> {
+    let Some(raw) = parse_path_flag(args, "--topology") else {
+        return DEFAULT_TOPOLOGY.to_vec();
+    };
+
+    let topology = raw
+        .split(',')
+        .map(|n| n.trim().parse::<usize>())
+        .collect::<Result<Vec<usize>, _>>();
+
+    match topology {
+        Ok(topology)
+            if topology.len() >= 2
+                && topology.first() == Some(&5)
+                && topology.last() == Some(&2)
+                && topology.iter().all(|&n| n > 0) =>
+        {
+            topology
+        }
+        _ => {
+            eprintln!(
+                "--topology must be a comma-separated list of positive layer sizes starting with 5 inputs and ending with 2 outputs, got {raw:?}; using the default topology instead"
+            );
+            DEFAULT_TOPOLOGY.to_vec()
+        }
+    }
+}
+
+/// Parse the `--train` flag into a number of generations, falling back
+/// to `None` (no training) if it's missing or not a positive integer.
+/// `train` always runs at least one generation, so a zero or
+/// unparseable value is rejected here rather than reaching it.
+pub fn parse_train_generations(args: &[String]) -> Option<usize> {
+    let raw = parse_path_flag(args, "--train")?;
+    match raw.parse::<usize>() {
+        Ok(generations) if generations > 0 => Some(generations),
+        _ => {
+            eprintln!(
+                "--train must be a positive number of generations, got {raw:?}; ignoring"
+            );
+            None
+        }
+    }
+}
+
+/// Parse a `--flag value` pair out of the command line arguments
+pub fn parse_path_flag(args: &[String], flag: &str) -> Option<String> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|i| args.get(i + 1))
+        .cloned()
+}
+
+/// Serialize a feller's brain to a JSON file at `path`
+pub fn save_champion(path: &str, champion: &Feller) {
+    let json =
+        serde_json::to_string_pretty(&champion.brain).expect("failed to serialize champion brain");
+    if let Err(e) = fs::write(path, json) {
+        eprintln!("failed to save champion brain to {path}: {e}");
+    }
+}
+
+/// Simulates a single step of the game
+pub fn simulate_step(
+    pipes: &mut Vec<Pipe>,
+    fellers: &mut [Feller],
+    rng: &mut StdRng,
+    step: i32,
+    width: f32,
+    height: f32,
+) {
+    simulate_pipes(pipes, rng, width);
+
+    for feller in fellers.iter_mut() {
+        if feller.is_alive {
+            simulate_feller(feller, pipes, step, width, height);
+        }
+    }
+}
+
+/// Move the pipes ahead, occasionally spawning new ones
+fn simulate_pipes(pipes: &mut Vec<Pipe>, rng: &mut StdRng, width: f32) {
+    // spawn a new pipe with a certain probability
+    if pipes.is_empty() || rng.random::<f32>() < PIPE_PROBABILITY {
+        let spawn_allowed = match pipes.last() {
+            Some(pipe) => pipe.x + PIPE_MIN_DISTANCE < width,
+            None => true,
+        };
+        if spawn_allowed {
+            pipes.push(Pipe::random(rng, width));
+        }
+    }
+
+    // update pipes
+    for pipe in pipes.iter_mut() {
+        pipe.x -= HSPEED;
+    }
+
+    // remove pipes that have left the screen
+    pipes.retain(|p| p.x + PIPE_WIDTH > 0.0);
+}
+
+/// Move a feller according to gravity and input (jumping)
+/// and check for collisions with environment objects
+fn simulate_feller(feller: &mut Feller, pipes: &mut Vec<Pipe>, step: i32, width: f32, height: f32) {
+    // update the feller based on the neural network's output
+    let closest_pipe = pipes.iter().find(|&p| p.x > FELLER_X);
+    if let Some(pipe) = closest_pipe {
+        let input = feller_input(feller, pipe, width, height);
+        let output = feller.predict(input);
+        if output[0] > output[1] {
+            feller.yspeed -= LIFT;
+        }
+    }
+
+    // Update the feller's vertical speed with gravitation
+    feller.yspeed = (feller.yspeed + 0.02).clamp(-FELLER_MAX_SPEED, FELLER_MAX_SPEED);
+    feller.y += feller.yspeed;
+
+    // Check for collisions with ceiling and floor
+    if feller.y < 0.0 || feller.y > height {
+        feller.is_alive = false;
+        feller.steps_survived = step
+    }
+
+    // Check for collisions with pipes, and count the ones just cleared
+    for pipe in pipes {
+        let trailing_edge = pipe.x + PIPE_WIDTH;
+        if trailing_edge < FELLER_X && trailing_edge + HSPEED >= FELLER_X {
+            feller.pipes_passed += 1;
+        }
+
+        if (pipe.x - FELLER_X).abs() < FELLER_R
+            && (feller.y - FELLER_R < pipe.y1 || feller.y + FELLER_R > pipe.y2)
+        {
+            feller.is_alive = false;
+            feller.steps_survived = step
+        }
+    }
+}
+
+/// Build the neural network input vector for a feller and the pipe ahead of it
+pub fn feller_input(feller: &Feller, pipe: &Pipe, width: f32, height: f32) -> Vec<f64> {
+    vec![
+        (feller.y / height) as f64,
+        (feller.yspeed / FELLER_MAX_SPEED) as f64,
+        (pipe.x / width) as f64,
+        (pipe.y1 / height) as f64,
+        (pipe.y2 / height) as f64,
+    ]
+}
+
+/// Compute a score for a feller
+pub fn score(feller: &Feller) -> f32 {
+    feller.steps_survived as f32 + feller.pipes_passed as f32 * PIPE_BONUS
+}
+
+/// Select the index of a feller from a normalized, scored population
+/// using fitness-proportional (roulette wheel) selection
+fn roulette_pick(scored_fellers: &[(f32, &Feller)], rng: &mut StdRng) -> usize {
+    let mut r = rng.random_range(0.0..1.0);
+    for (i, (score, _)) in scored_fellers.iter().enumerate() {
+        r -= score;
+        if r <= 0.0 {
+            return i;
+        }
+    }
+    scored_fellers.len() - 1
+}
+
+/// Pipes are the fellers' main obstacles.
+/// Fellers must fly through the hole in the middle
+/// of the pipe to survive.
+pub struct Pipe {
+    /// x-coordinate of the pipe
+    pub x: f32,
+    /// y-coordinate of the top of the hole
+    pub y1: f32,
+    /// y-coordinate of the bottom of the hole
+    pub y2: f32,
+}
+
+impl Pipe {
+    /// create a pipe with a randomized hole
+    pub fn random(rng: &mut StdRng, width: f32) -> Pipe {
+        let y1 = rng.random_range(100.0..200.0);
+        let y2 = y1 + rng.random_range(PIPE_MIN_APERTURE..PIPE_MAX_APERTURE);
+        Pipe { x: width, y1, y2 }
+    }
+}
+
+/// Flappy fellers are the heroes of this story.
+/// They are being controlled by an evolving AI.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Feller {
+    /// y-coordinate of the feller
+    pub y: f32,
+    /// vertical speed of the feller
+    yspeed: f32,
+    /// the AI that controls the feller
+    brain: NeuralNetwork,
+    /// flag indicating if the feller is still alive
+    pub is_alive: bool,
+    /// counter of how many simulation steps
+    /// this feller has survived
+    steps_survived: i32,
+    /// counter of how many pipes this feller has flown through
+    pub pipes_passed: i32,
+}
+
+impl Feller {
+    /// Creates a new feller with a randomized neural network of the
+    /// given topology (layer sizes) for a brain, at the default height
+    pub fn new(topology: &[usize], height: f32) -> Feller {
+        let mut rng = StdRng::from_os_rng();
+        let mut brain = NeuralNetwork::new_from_topology(topology, Some(&mut rng));
+        brain.set_activation_function(ActivationFunction::Sigmoid);
+        Feller {
+            y: height / 3.0,
+            yspeed: 0.0,
+            brain,
+            is_alive: true,
+            steps_survived: 0,
+            pipes_passed: 0,
+        }
+    }
+
+    /// ask the feller for their move during a simulation step
+    fn predict(&self, input: Vec<f64>) -> Vec<f64> {
+        self.brain.predict(input)
+    }
+
+    /// mutate the brain of the feller ðŸ§Ÿâ€â™‚ï¸
+    fn mutate(&mut self, rng: &mut StdRng) {
+        self.brain.mutate(rng, MUTATION_RATE);
+    }
+
+    /// create a new feller from a previously trained brain,
+    /// e.g. one loaded from disk, and otherwise default properties
+    pub fn from_brain(brain: NeuralNetwork, height: f32) -> Feller {
+        Feller {
+            y: height / 3.0,
+            yspeed: 0.0,
+            brain,
+            is_alive: true,
+            steps_survived: 0,
+            pipes_passed: 0,
+        }
+    }
+
+    /// create a new feller whose brain combines this feller's brain
+    /// with `other`'s via uniform crossover, picking each weight and
+    /// bias from either parent with equal probability
+    fn crossover(&self, other: &Feller, rng: &mut StdRng, height: f32) -> Feller {
+        Feller {
+            y: height / 3.0,
+            yspeed: 0.0,
+            brain: self.brain.crossover(&other.brain, rng),
+            is_alive: true,
+            steps_survived: 0,
+            pipes_passed: 0,
+        }
+    }
+
+    /// overwrite this feller's simulation state and brain with
+    /// `other`'s, reusing its existing slot in a population's
+    /// double-buffered `Vec` instead of replacing it wholesale
+    fn reset_from(&mut self, other: Feller) {
+        self.y = other.y;
+        self.yspeed = other.yspeed;
+        self.brain = other.brain;
+        self.is_alive = other.is_alive;
+        self.steps_survived = other.steps_survived;
+        self.pipes_passed = other.pipes_passed;
+    }
+
+    /// consume the feller and return its brain, e.g. to seed a new
+    /// population from a trained champion
+    pub fn into_brain(self) -> NeuralNetwork {
+        self.brain
+    }
+
+    /// the weights of the feller's brain, per layer and per neuron,
+    /// for visualizing the network
+    pub fn weights(&self) -> &Vec<Vec<Vec<f64>>> {
+        self.brain.weights()
+    }
+
+    /// the activation of every neuron in every layer, including the
+    /// input layer, for the given input, for visualizing the network
+    pub fn activations(&self, input: Vec<f64>) -> Vec<Vec<f64>> {
+        self.brain.activations(input)
+    }
+}
+
+/// Two owned buffers that alternate between being "active" (the current
+/// generation) and "inactive" (scratch space to build the next one into),
+/// so a population's offspring can be written into already-allocated
+/// slots instead of growing a brand new `Vec` every generation
+struct DoubleBuffer<T> {
+    first: Vec<T>,
+    second: Vec<T>,
+    active_is_first: bool,
+}
+
+impl<T> DoubleBuffer<T> {
+    fn new(first: Vec<T>, second: Vec<T>) -> DoubleBuffer<T> {
+        DoubleBuffer {
+            first,
+            second,
+            active_is_first: true,
+        }
+    }
+
+    /// the buffer currently in use
+    fn active(&self) -> &[T] {
+        if self.active_is_first {
+            &self.first
+        } else {
+            &self.second
+        }
+    }
+
+    /// the buffer currently in use, mutably
+    fn active_mut(&mut self) -> &mut [T] {
+        if self.active_is_first {
+            &mut self.first
+        } else {
+            &mut self.second
+        }
+    }
+
+    /// the active and inactive buffers, borrowed together so the
+    /// inactive one can be written into while reading from the active one
+    fn split(&mut self) -> (&[T], &mut [T]) {
+        if self.active_is_first {
+            (&self.first, &mut self.second)
+        } else {
+            (&self.second, &mut self.first)
+        }
+    }
+
+    /// make the inactive buffer active, and vice versa
+    fn switch(&mut self) {
+        self.active_is_first = !self.active_is_first;
+    }
+}
+
+/// Population of fellers that competete against each other
+pub struct Population {
+    /// The fellers of this generation and the next, double-buffered
+    buffer: DoubleBuffer<Feller>,
+    /// The network topology (layer sizes) shared by every feller in
+    /// this population, inherited by all of their offspring
+    topology: Vec<usize>,
+}
+
+impl Population {
+    /// Create a new population of fellers of the desired size and topology
+    pub fn new(size: usize, height: f32, topology: &[usize]) -> Population {
+        let first = (0..size).map(|_| Feller::new(topology, height)).collect();
+        let second = (0..size).map(|_| Feller::new(topology, height)).collect();
+        Population {
+            buffer: DoubleBuffer::new(first, second),
+            topology: topology.to_vec(),
+        }
+    }
+
+    /// The fellers of the current generation
+    pub fn fellers(&self) -> &[Feller] {
+        self.buffer.active()
+    }
+
+    /// The fellers of the current generation, mutably
+    pub fn fellers_mut(&mut self) -> &mut [Feller] {
+        self.buffer.active_mut()
+    }
+
+    /// Determines if the population is still alive.
+    /// A population is alive if at least one of its fellers
+    /// is still alive.
+    pub fn is_alive(&self) -> bool {
+        for feller in self.fellers() {
+            if feller.is_alive {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Returns the number of fellers that are still alive
+    pub fn survivor_count(&self) -> usize {
+        let mut count = 0;
+        for feller in self.fellers() {
+            if feller.is_alive {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Returns the feller with the highest `score`
+    pub fn champion(&self) -> &Feller {
+        self.fellers()
+            .iter()
+            .max_by(|a, b| score(a).total_cmp(&score(b)))
+            .expect("population should never be empty")
+    }
+
+    /// Seed a new population of `POPULATION_SIZE` fellers from a single
+    /// champion brain: one unmutated copy of it plus mutated clones
+    pub fn from_brain(brain: NeuralNetwork, height: f32) -> Population {
+        let topology = brain.topology();
+        let mut rng = StdRng::from_os_rng();
+        let mut first = vec![Feller::from_brain(brain.clone(), height)];
+        for _ in 1..POPULATION_SIZE {
+            let mut feller = Feller::from_brain(brain.clone(), height);
+            feller.mutate(&mut rng);
+            first.push(feller);
+        }
+        let second = (0..POPULATION_SIZE)
+            .map(|_| Feller::new(&topology, height))
+            .collect();
+        Population {
+            buffer: DoubleBuffer::new(first, second),
+            topology,
+        }
+    }
+
+    /// Load a champion brain from a JSON file and seed a new population
+    /// of `POPULATION_SIZE` fellers from it, as `from_brain` does
+    pub fn from_champion_file(path: &str, height: f32) -> std::io::Result<Population> {
+        let json = fs::read_to_string(path)?;
+        let brain: NeuralNetwork = serde_json::from_str(&json)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        Ok(Population::from_brain(brain, height))
+    }
+
+    /// Advance to the next generation in place: score and select parents
+    /// from the active buffer, breed and mutate children into the
+    /// already-allocated slots of the inactive buffer, then switch the
+    /// two buffers so the children become the active generation
+    pub fn advance_generation(&mut self, height: f32) {
+        let topology = self.topology.clone();
+        let (active, inactive) = self.buffer.split();
+
+        // compute a score for each feller
+        // then sort them by descending score
+        // and retain only the top 5%
+        let mut scored_fellers = active
+            .iter()
+            .map(|f| (score(f), f))
+            .map(|(s, f)| (s * s, f))
+            .collect::<Vec<_>>();
+        scored_fellers.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let keep_len = (POPULATION_SIZE as f64 * 0.05).ceil() as usize;
+        scored_fellers.truncate(keep_len);
+
+        // normalize scores
+        let mut score_sum = 0.0;
+        for (score, _) in &scored_fellers {
+            score_sum += score;
+        }
+        let scored_fellers = scored_fellers
+            .into_iter()
+            .map(|(s, f)| (s / score_sum, f))
+            .collect::<Vec<_>>();
+
+        let mut rng = StdRng::from_os_rng();
+
+        // breed 80% of the next generation by crossing two parents,
+        // drawn via fitness-proportional roulette selection, while the
+        // highest scorers are most likely to procreate, writing each
+        // child directly into the inactive buffer's existing slot
+        let procreation_len = (0.8 * POPULATION_SIZE as f64).ceil() as usize;
+        for slot in inactive.iter_mut().take(procreation_len) {
+            let mother = roulette_pick(&scored_fellers, &mut rng);
+            let mut father = roulette_pick(&scored_fellers, &mut rng);
+            while father == mother && scored_fellers.len() > 1 {
+                father = roulette_pick(&scored_fellers, &mut rng);
+            }
+
+            let mut child =
+                scored_fellers[mother]
+                    .1
+                    .crossover(scored_fellers[father].1, &mut rng, height);
+            child.mutate(&mut rng);
+            slot.reset_from(child);
+        }
+
+        // fill up the remaining slots with random new fellers
+        for slot in inactive.iter_mut().skip(procreation_len) {
+            slot.reset_from(Feller::new(&topology, height));
+        }
+
+        self.buffer.switch();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(flags: &[&str]) -> Vec<String> {
+        std::iter::once("flappy-fellers".to_string())
+            .chain(flags.iter().map(|s| s.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parse_topology_falls_back_when_missing() {
+        assert_eq!(parse_topology(&args(&[])), DEFAULT_TOPOLOGY.to_vec());
+    }
+
+    #[test]
+    fn parse_topology_accepts_extra_hidden_layers() {
+        assert_eq!(
+            parse_topology(&args(&["--topology", "5,9,9,2"])),
+            vec![5, 9, 9, 2]
+        );
+    }
+
+    #[test]
+    fn parse_topology_rejects_zero_sized_layer() {
+        assert_eq!(
+            parse_topology(&args(&["--topology", "5,0,2"])),
+            DEFAULT_TOPOLOGY.to_vec()
+        );
+    }
+
+    #[test]
+    fn parse_topology_rejects_wrong_input_count() {
+        assert_eq!(
+            parse_topology(&args(&["--topology", "4,4,2"])),
+            DEFAULT_TOPOLOGY.to_vec()
+        );
+    }
+
+    #[test]
+    fn parse_topology_rejects_missing_output_layer() {
+        assert_eq!(
+            parse_topology(&args(&["--topology", "5,4"])),
+            DEFAULT_TOPOLOGY.to_vec()
+        );
+    }
+
+    #[test]
+    fn parse_topology_rejects_unparseable_layer() {
+        assert_eq!(
+            parse_topology(&args(&["--topology", "bogus"])),
+            DEFAULT_TOPOLOGY.to_vec()
+        );
+    }
+
+    #[test]
+    fn roulette_pick_always_picks_the_only_feller() {
+        let feller = Feller::new(DEFAULT_TOPOLOGY, HEADLESS_HEIGHT);
+        let scored = [(1.0, &feller)];
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..20 {
+            assert_eq!(roulette_pick(&scored, &mut rng), 0);
+        }
+    }
+
+    #[test]
+    fn roulette_pick_stays_in_bounds_so_distinct_parents_can_be_drawn() {
+        let a = Feller::new(DEFAULT_TOPOLOGY, HEADLESS_HEIGHT);
+        let b = Feller::new(DEFAULT_TOPOLOGY, HEADLESS_HEIGHT);
+        let c = Feller::new(DEFAULT_TOPOLOGY, HEADLESS_HEIGHT);
+        let scored = [(0.2, &a), (0.3, &b), (0.5, &c)];
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut seen = std::collections::HashSet::new();
+        for _ in 0..100 {
+            let i = roulette_pick(&scored, &mut rng);
+            assert!(i < scored.len());
+            seen.insert(i);
+        }
+        // with three fellers sharing the probability mass, a long enough
+        // run should be able to draw a distinct mother and father
+        assert!(seen.len() > 1);
+    }
+}
@@ -1,50 +1,45 @@
+use flappy_fellers::{
+    FELLER_X, Feller, PIPE_WIDTH, POPULATION_SIZE, Pipe, Population, feller_input, parse_path_flag,
+    parse_topology, save_champion, score, simulate_step,
+};
 use macroquad::{
     color::*,
     input::{KeyCode, is_key_pressed},
     prelude::ImageFormat,
-    shapes::draw_rectangle,
+    shapes::{draw_circle, draw_line, draw_rectangle},
     text::draw_text,
     texture::{Texture2D, draw_texture},
     window::{clear_background, next_frame, screen_height, screen_width},
 };
-use neural_network_study::{ActivationFunction, NeuralNetwork};
 use rand::prelude::*;
-use serde::{Deserialize, Serialize};
-
-/// Speed at which the pipes move in pixels per iteration
-const HSPEED: f32 = 0.8;
-/// Maximum vertical speed of a flappy feller
-const FELLER_MAX_SPEED: f32 = 2.0;
-/// Probability of spawning a pipe during an iteration
-const PIPE_PROBABILITY: f32 = 0.002;
-/// Pipe width
-const PIPE_WIDTH: f32 = 40.0;
-/// Minimum size of the pipe aperture (hole)
-const PIPE_MIN_APERTURE: f32 = 80.0;
-/// Maximum size of the pipe aperture
-const PIPE_MAX_APERTURE: f32 = 160.0;
-/// Minimum distance between two pipes
-const PIPE_MIN_DISTANCE: f32 = 160.0;
-/// Jumping force
-const LIFT: f32 = 2.0;
-/// x-coordinate of the fellers
-const FELLER_X: f32 = 40.0;
-/// body radius of the fellers
-const FELLER_R: f32 = 20.0;
-/// Number of fellers in each generation
-const POPULATION_SIZE: usize = 150;
-/// Probability of mutation of weights
-/// during cloning of neural network
-const MUTATION_RATE: f64 = 0.1;
 
 /// main function simulates and displays the game
 #[macroquad::main("Flappy Feller")]
 async fn main() {
+    // paths to load the starting champion brain from and/or save
+    // the champion brain to at the end of every generation
+    let args: Vec<String> = std::env::args().collect();
+    let load_path = parse_path_flag(&args, "--load");
+    let save_path = parse_path_flag(&args, "--save");
+    // the network topology (layer sizes) shared by every feller in
+    // the population and inherited by all of their offspring
+    let topology = parse_topology(&args);
+
     let mut rng = StdRng::from_os_rng();
     // a vec to hold the pipes for fellers to crash into
     let mut pipes: Vec<Pipe> = vec![];
-    // a collection of fellers flapping alongside each other
-    let mut population = Population::new(POPULATION_SIZE);
+    // a collection of fellers flapping alongside each other, seeded
+    // either from a saved champion brain or from scratch. A trained
+    // champion is loaded the same way, via `--load` on a file produced
+    // by the headless `train` binary
+    let mut population = if let Some(path) = &load_path {
+        Population::from_champion_file(path, screen_height()).unwrap_or_else(|e| {
+            eprintln!("failed to load champion from {path}: {e}");
+            Population::new(POPULATION_SIZE, screen_height(), &topology)
+        })
+    } else {
+        Population::new(POPULATION_SIZE, screen_height(), &topology)
+    };
     // the number of steps simulated during each frame.
     // this allows to speed up the training process
     let mut iterations_per_frame = 1;
@@ -52,6 +47,8 @@ async fn main() {
     let mut steps = 0;
     // generation counter used purely for visualization
     let mut generation = 1;
+    // highest score reached by any champion across all generations so far
+    let mut max_score: f32 = 0.0;
     // graphics resources
     let walden_sprite = Texture2D::from_file_with_format(
         include_bytes!("../assets/walden.png"),
@@ -63,16 +60,27 @@ async fn main() {
 
         // simulate one or more steps of the game
         for _ in 0..iterations_per_frame {
-            simulate_step(&mut pipes, &mut population.fellers, &mut rng, steps);
+            simulate_step(
+                &mut pipes,
+                population.fellers_mut(),
+                &mut rng,
+                steps,
+                screen_width(),
+                screen_height(),
+            );
             steps += 1;
         }
 
         // spawn a new population once the current one has expired
         if !population.is_alive() {
+            max_score = max_score.max(score(population.champion()));
+            if let Some(path) = &save_path {
+                save_champion(path, population.champion());
+            }
             steps = 0;
             generation += 1;
             pipes.clear();
-            population = Population::from_predecessors(population);
+            population.advance_generation(screen_height());
         }
 
         draw_scene(
@@ -81,6 +89,7 @@ async fn main() {
             &walden_sprite,
             generation,
             iterations_per_frame,
+            max_score,
         );
 
         next_frame().await
@@ -94,6 +103,7 @@ fn draw_scene(
     walden_sprite: &Texture2D,
     generation: usize,
     iterations_per_frame: usize,
+    max_score: f32,
 ) {
     // draw the scene
     clear_background(WHITE);
@@ -111,7 +121,7 @@ fn draw_scene(
     }
 
     // draw the feller
-    for feller in &population.fellers {
+    for feller in population.fellers() {
         if feller.is_alive {
             // let color = Color::from_rgba(0, 0, 0, 64);
             // draw_circle(FELLER_X, feller.y, FELLER_R, color);
@@ -127,16 +137,88 @@ fn draw_scene(
     // draw the HUD
     draw_text(
         &format!(
-            "Generation {}; Fellers: {}; Speed: {}",
+            "Generation {}; Fellers: {}; Speed: {}; Max score: {}",
             generation,
             population.survivor_count(),
-            iterations_per_frame
+            iterations_per_frame,
+            max_score
         ),
         20.0,
         20.0,
         20.0,
         BLUE,
     );
+
+    // draw a live diagram of the network steering the current best
+    // feller, picked by its live pipes_passed count
+    if let Some(feller) = population
+        .fellers()
+        .iter()
+        .filter(|f| f.is_alive)
+        .max_by_key(|f| f.pipes_passed)
+    {
+        if let Some(pipe) = pipes.iter().find(|&p| p.x > FELLER_X) {
+            let input = feller_input(feller, pipe, screen_width(), screen_height());
+            draw_network_overlay(feller, input, screen_width() - 160.0, 100.0);
+        }
+    }
+}
+
+/// Draws a HUD panel visualizing a feller's neural network: one column
+/// of circles per layer, connected by lines colored and thickened by
+/// weight sign and magnitude, with input nodes shaded by their current
+/// activation value
+fn draw_network_overlay(feller: &Feller, input: Vec<f64>, origin_x: f32, origin_y: f32) {
+    const LAYER_GAP: f32 = 60.0;
+    const NODE_GAP: f32 = 24.0;
+    const NODE_R: f32 = 6.0;
+
+    let activations = feller.activations(input);
+    let weights = feller.weights();
+
+    let layer_x = |layer: usize| origin_x + layer as f32 * LAYER_GAP;
+    let node_y = |count: usize, index: usize| {
+        origin_y + index as f32 * NODE_GAP - (count as f32 - 1.0) * NODE_GAP / 2.0
+    };
+
+    // draw the connections first so the nodes are drawn on top
+    for (layer, layer_weights) in weights.iter().enumerate() {
+        for (to, neuron_weights) in layer_weights.iter().enumerate() {
+            for (from, &w) in neuron_weights.iter().enumerate() {
+                let alpha = (80.0 + (w.abs() as f32).min(1.0) * 175.0) as u8;
+                let color = if w >= 0.0 {
+                    Color::from_rgba(0, 160, 0, alpha)
+                } else {
+                    Color::from_rgba(200, 0, 0, alpha)
+                };
+                draw_line(
+                    layer_x(layer),
+                    node_y(activations[layer].len(), from),
+                    layer_x(layer + 1),
+                    node_y(activations[layer + 1].len(), to),
+                    (w.abs() as f32).clamp(0.5, 3.0),
+                    color,
+                );
+            }
+        }
+    }
+
+    // draw the nodes, shading the input layer by its activation value
+    for (layer, layer_activations) in activations.iter().enumerate() {
+        for (index, &activation) in layer_activations.iter().enumerate() {
+            let shade = if layer == 0 {
+                (activation.clamp(0.0, 1.0) * 255.0) as u8
+            } else {
+                200
+            };
+            draw_circle(
+                layer_x(layer),
+                node_y(layer_activations.len(), index),
+                NODE_R,
+                Color::from_rgba(shade, shade, 255, 255),
+            );
+        }
+    }
 }
 
 /// Handle keyboard input from the user
@@ -154,261 +236,3 @@ fn handle_input(iterations_per_frame: &mut usize) {
 
     *iterations_per_frame = (*iterations_per_frame).clamp(1, 100);
 }
-
-/// Simulates a single step of the game
-fn simulate_step(pipes: &mut Vec<Pipe>, fellers: &mut [Feller], rng: &mut StdRng, step: i32) {
-    simulate_pipes(pipes, rng);
-
-    for feller in fellers.iter_mut() {
-        if feller.is_alive {
-            simulate_feller(feller, pipes, step);
-        }
-    }
-}
-
-/// Move the pipes ahead, occasionally spawning new ones
-fn simulate_pipes(pipes: &mut Vec<Pipe>, rng: &mut StdRng) {
-    // spawn a new pipe with a certain probability
-    if pipes.is_empty() || rng.random::<f32>() < PIPE_PROBABILITY {
-        let spawn_allowed = match pipes.last() {
-            Some(pipe) => pipe.x + PIPE_MIN_DISTANCE < screen_width(),
-            None => true,
-        };
-        if spawn_allowed {
-            pipes.push(Pipe::random(rng));
-        }
-    }
-
-    // update pipes
-    for pipe in pipes.iter_mut() {
-        pipe.x -= HSPEED;
-    }
-
-    // remove pipes that have left the screen
-    pipes.retain(|p| p.x + PIPE_WIDTH > 0.0);
-}
-
-/// Move a feller according to gravity and input (jumping)
-/// and check for collisions with environment objects
-fn simulate_feller(feller: &mut Feller, pipes: &mut Vec<Pipe>, step: i32) {
-    // update the feller based on the neural network's output
-    let closest_pipe = pipes.iter().find(|&p| p.x > FELLER_X);
-    if let Some(pipe) = closest_pipe {
-        let w = screen_width();
-        let h = screen_height();
-        let input = vec![
-            (feller.y / h) as f64,
-            (feller.yspeed / FELLER_MAX_SPEED) as f64,
-            (pipe.x / w) as f64,
-            (pipe.y1 / h) as f64,
-            (pipe.y2 / h) as f64,
-        ];
-        let output = feller.predict(input);
-        if output[0] > output[1] {
-            feller.yspeed -= LIFT;
-        }
-    }
-
-    // Update the feller's vertical speed with gravitation
-    feller.yspeed = (feller.yspeed + 0.02).clamp(-FELLER_MAX_SPEED, FELLER_MAX_SPEED);
-    feller.y += feller.yspeed;
-
-    // Check for collisions with ceiling and floor
-    if feller.y < 0.0 || feller.y > screen_height() {
-        feller.is_alive = false;
-        feller.steps_survived = step
-    }
-
-    // Check for collisions with pipes
-    for pipe in pipes {
-        if (pipe.x - FELLER_X).abs() < FELLER_R
-            && (feller.y - FELLER_R < pipe.y1 || feller.y + FELLER_R > pipe.y2)
-        {
-            feller.is_alive = false;
-            feller.steps_survived = step
-        }
-    }
-}
-
-/// Compute a score for a feller
-fn score(feller: &Feller) -> f32 {
-    feller.steps_survived as f32
-}
-
-/// Pipes are the fellers' main obstacles.
-/// Fellers must fly through the hole in the middle
-/// of the pipe to survive.
-struct Pipe {
-    /// x-coordinate of the pipe
-    x: f32,
-    /// y-coordinate of the top of the hole
-    y1: f32,
-    /// y-coordinate of the bottom of the hole
-    y2: f32,
-}
-
-impl Pipe {
-    /// create a pipe with a randomized hole
-    pub fn random(rng: &mut StdRng) -> Pipe {
-        let y1 = rng.random_range(100.0..200.0);
-        let y2 = y1 + rng.random_range(PIPE_MIN_APERTURE..PIPE_MAX_APERTURE);
-        Pipe {
-            x: screen_width(),
-            y1,
-            y2,
-        }
-    }
-}
-
-/// Flappy fellers are the heroes of this story.
-/// They are being controlled by an evolving AI.
-#[derive(Serialize, Deserialize)]
-struct Feller {
-    /// y-coordinate of the feller
-    y: f32,
-    /// vertical speed of the feller
-    yspeed: f32,
-    /// the AI that controls the feller
-    brain: NeuralNetwork,
-    /// flag indicating if the feller is still alive
-    is_alive: bool,
-    /// counter of how many simulation steps
-    /// this feller has survived
-    steps_survived: i32,
-}
-
-impl Feller {
-    /// Creates a new feller with a randomized neural network
-    /// for a brain and at the default height
-    fn new() -> Feller {
-        let mut rng = StdRng::from_os_rng();
-        let mut brain = NeuralNetwork::new(5, 4, 2, Some(&mut rng));
-        brain.set_activation_function(ActivationFunction::Sigmoid);
-        Feller {
-            y: screen_height() / 3.0,
-            yspeed: 0.0,
-            brain,
-            is_alive: true,
-            steps_survived: 0,
-        }
-    }
-
-    /// ask the feller for their move during a simulation step
-    fn predict(&self, input: Vec<f64>) -> Vec<f64> {
-        self.brain.predict(input)
-    }
-
-    /// mutate the brain of the feller ðŸ§Ÿâ€â™‚ï¸
-    fn mutate(&mut self, rng: &mut StdRng) {
-        self.brain.mutate(rng, MUTATION_RATE);
-    }
-
-    /// create a new feller with a clone of this one's brain
-    /// and otherwise default properties
-    fn spawn(&self) -> Feller {
-        Feller {
-            y: screen_height() / 3.0,
-            yspeed: 0.0,
-            brain: self.brain.clone(),
-            is_alive: true,
-            steps_survived: 0,
-        }
-    }
-}
-
-/// Population of fellers that competete against each other
-struct Population {
-    /// The fellers of this generation
-    fellers: Vec<Feller>,
-}
-
-impl Population {
-    /// Create a new population of fellers of the desired size
-    fn new(size: usize) -> Population {
-        let mut fellers = vec![];
-        for _ in 0..size {
-            fellers.push(Feller::new());
-        }
-        Population { fellers }
-    }
-
-    /// Determines if the population is still alive.
-    /// A population is alive if at least one of its fellers
-    /// is still alive.
-    fn is_alive(&self) -> bool {
-        for feller in &self.fellers {
-            if feller.is_alive {
-                return true;
-            }
-        }
-        false
-    }
-
-    /// Returns the number of fellers that are still alive
-    fn survivor_count(&self) -> usize {
-        let mut count = 0;
-        for feller in &self.fellers {
-            if feller.is_alive {
-                count += 1;
-            }
-        }
-        count
-    }
-
-    /// Spawn a new population of fellers
-    /// by scoring the ones in this generation
-    /// and cloning the best ones
-    fn from_predecessors(predecessors: Population) -> Population {
-        // compute a score for each feller
-        // then sort them by descending score
-        // and retain only the top 5%
-        let mut scored_fellers = predecessors
-            .fellers
-            .into_iter()
-            .map(|p| (score(&p), p))
-            .map(|(s, p)| (s * s, p))
-            .collect::<Vec<_>>();
-        scored_fellers.sort_by(|a, b| b.0.total_cmp(&a.0));
-        let keep_len = (POPULATION_SIZE as f64 * 0.05).ceil() as usize;
-        scored_fellers.truncate(keep_len);
-
-        // normalize scores
-        let mut score_sum = 0.0;
-        for (score, _) in &scored_fellers {
-            score_sum += score;
-        }
-        let scored_fellers = scored_fellers
-            .into_iter()
-            .map(|(s, f)| (s / score_sum, f))
-            .collect::<Vec<_>>();
-
-        let mut descendants = vec![];
-        let mut rng = StdRng::from_os_rng();
-
-        // create 80% of the new descendants by random picking
-        // while the highest scorers are most likely to procreate
-        let procreation_len = (0.8 * POPULATION_SIZE as f64).ceil() as usize;
-        while descendants.len() < procreation_len {
-            let mut r = rng.random_range(0.0..1.0);
-
-            for (score, feller) in &scored_fellers {
-                r -= score;
-                if r <= 0.0 {
-                    let mut child = feller.spawn();
-                    child.mutate(&mut rng);
-                    descendants.push(child);
-                    break;
-                }
-            }
-        }
-
-        // fill up the remaining slots with random new fellers
-        while descendants.len() < POPULATION_SIZE {
-            descendants.push(Feller::new());
-        }
-
-        Population {
-            fellers: descendants,
-        }
-    }
-}